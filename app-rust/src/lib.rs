@@ -1,11 +1,24 @@
 use spin_sdk::http::{IntoResponse, Request, Response, Method};
 use spin_sdk::http_component;
+use spin_sdk::key_value::Store;
+use spin_sdk::variables;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 // 임계값 상수
 const RESPONSE_TIME_THRESHOLD: u32 = 2000;
 const TEMPERATURE_THRESHOLD: f32 = 80.0;
 
+// 히스테리시스: 경보가 해제되려면 값이 (임계값 - 여유분) 밑으로 내려가야 함
+const RESPONSE_TIME_HYSTERESIS: u32 = 200;
+const TEMPERATURE_HYSTERESIS: f32 = 5.0;
+
+// 디바운스: 연속으로 이 횟수만큼 임계값을 넘어야 Alarm 상태로 전이
+const DEBOUNCE_COUNT: u32 = 3;
+
+const DEVICE_STATE_STORE: &str = "default";
+
 #[derive(Deserialize)]
 struct Log {
     #[serde(default = "default_device_id")]
@@ -25,21 +38,472 @@ struct AnalysisResult {
     status: String,
     alerts: Vec<String>,
     device_id: String,
+    state: String,
+    state_changed: bool,
+}
+
+// 디바이스별 상태 머신. Normal -> Warning -> Alarm 순으로 전이하며,
+// Alarm 해제는 히스테리시스 여유분 아래로 내려온 뒤에만 일어난다.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum DeviceState {
+    Normal,
+    Warning,
+    Alarm,
+}
+
+impl DeviceState {
+    fn as_str(&self) -> &'static str {
+        match self {
+            DeviceState::Normal => "Normal",
+            DeviceState::Warning => "Warning",
+            DeviceState::Alarm => "Alarm",
+        }
+    }
+}
+
+// 디바이스 키-값 저장소에 영속되는 상태.
+// last_temperature/last_response_time은 각 지표가 마지막으로 "실제로 보고된" 값을 기억해서,
+// 한 요청이 일부 필드를 생략하더라도 그 지표의 회복 여부를 안전하게 판단할 수 있게 한다.
+#[derive(Serialize, Deserialize)]
+struct DeviceRecord {
+    state: DeviceState,
+    consecutive_breaches: u32,
+    last_temperature: Option<f32>,
+    last_response_time: Option<u32>,
+    last_ts: u64,
+}
+
+impl DeviceRecord {
+    fn initial() -> Self {
+        DeviceRecord {
+            state: DeviceState::Normal,
+            consecutive_breaches: 0,
+            last_temperature: None,
+            last_response_time: None,
+            last_ts: 0,
+        }
+    }
+
+    fn load(store: &Store, device_id: &str) -> Self {
+        store
+            .get(&device_key(device_id))
+            .ok()
+            .flatten()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_else(DeviceRecord::initial)
+    }
+
+    fn save(&self, store: &Store, device_id: &str) -> anyhow::Result<()> {
+        let bytes = serde_json::to_vec(self)?;
+        store.set(&device_key(device_id), &bytes)?;
+        Ok(())
+    }
+}
+
+fn device_key(device_id: &str) -> String {
+    format!("devices/{}/state", device_id)
+}
+
+// 디바이스 레지스트리: 알려진 디바이스인지 판단하고 마지막으로 수신한 시각을 추적한다
+#[derive(Serialize, Deserialize)]
+struct DeviceRegistryEntry {
+    device_id: String,
+    first_seen: u64,
+    last_seen: u64,
+    log_count: u64,
+}
+
+fn registry_key(device_id: &str) -> String {
+    format!("devices/{}/meta", device_id)
+}
+
+// 아직 로그를 보낸 적 없는 디바이스라도 레지스트리에 등록해, GET /devices/{id}가 404를 내지 않게 한다
+// (예: 임계값만 먼저 설정된 디바이스). 이미 등록돼 있으면 log_count/last_seen은 건드리지 않는다
+fn ensure_registered(store: &Store, device_id: &str, ts: u64) -> anyhow::Result<()> {
+    if store.get(&registry_key(device_id))?.is_some() {
+        return Ok(());
+    }
+    let entry = DeviceRegistryEntry {
+        device_id: device_id.to_string(),
+        first_seen: ts,
+        last_seen: ts,
+        log_count: 0,
+    };
+    store.set(&registry_key(device_id), &serde_json::to_vec(&entry)?)?;
+    Ok(())
+}
+
+fn touch_registry(store: &Store, device_id: &str, ts: u64) -> anyhow::Result<()> {
+    let mut entry = store
+        .get(&registry_key(device_id))
+        .ok()
+        .flatten()
+        .and_then(|bytes| serde_json::from_slice::<DeviceRegistryEntry>(&bytes).ok())
+        .unwrap_or_else(|| DeviceRegistryEntry {
+            device_id: device_id.to_string(),
+            first_seen: ts,
+            last_seen: ts,
+            log_count: 0,
+        });
+    entry.last_seen = ts;
+    entry.log_count += 1;
+    store.set(&registry_key(device_id), &serde_json::to_vec(&entry)?)?;
+    Ok(())
+}
+
+// 개별 로그를 시계열로 영속화하기 위한 저장 레코드
+#[derive(Serialize, Deserialize)]
+struct StoredLogEntry {
+    timestamp: u64,
+    level: Option<String>,
+    response_time: Option<u32>,
+    temperature: Option<f32>,
+    message: Option<String>,
+    alert_kinds: Vec<String>,
+}
+
+fn log_key(device_id: &str, ts: u64, seq: u64) -> String {
+    format!("devices/{}/logs/{:020}-{:020}", device_id, ts, seq)
+}
+
+fn log_key_prefix(device_id: &str) -> String {
+    format!("devices/{}/logs/", device_id)
+}
+
+fn log_seq_key(device_id: &str) -> String {
+    format!("devices/{}/log_seq", device_id)
+}
+
+// 같은 초에 여러 건이 들어와도 키가 겹치지 않도록, 디바이스별 단조 증가 시퀀스를 키에 덧붙인다.
+// (배치 수신은 루프 안에서 같은 now_unix() 값을 여러 번 쓰기 때문에 타임스탬프만으로는 충돌한다)
+fn next_log_seq(store: &Store, device_id: &str) -> anyhow::Result<u64> {
+    let key = log_seq_key(device_id);
+    let seq = store
+        .get(&key)?
+        .and_then(|bytes| std::str::from_utf8(&bytes).ok()?.parse::<u64>().ok())
+        .unwrap_or(0)
+        + 1;
+    store.set(&key, seq.to_string().as_bytes())?;
+    Ok(seq)
+}
+
+fn store_log_entry(
+    store: &Store,
+    device_id: &str,
+    ts: u64,
+    log: &Log,
+    alert_kinds: &[String],
+) -> anyhow::Result<()> {
+    let entry = StoredLogEntry {
+        timestamp: ts,
+        level: log.level.clone(),
+        response_time: log.response_time,
+        temperature: log.temperature,
+        message: log.message.clone(),
+        alert_kinds: alert_kinds.to_vec(),
+    };
+    let seq = next_log_seq(store, device_id)?;
+    store.set(&log_key(device_id, ts, seq), &serde_json::to_vec(&entry)?)?;
+    touch_registry(store, device_id, ts)?;
+    Ok(())
+}
+
+// 숫자 컬럼에 대한 min/max/avg 롤업
+#[derive(Serialize)]
+struct MetricRollup {
+    count: u32,
+    min: f32,
+    max: f32,
+    avg: f32,
+}
+
+impl MetricRollup {
+    fn from_values(values: &[f32]) -> Option<Self> {
+        if values.is_empty() {
+            return None;
+        }
+        let min = values.iter().cloned().fold(f32::INFINITY, f32::min);
+        let max = values.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+        let avg = values.iter().sum::<f32>() / values.len() as f32;
+        Some(MetricRollup {
+            count: values.len() as u32,
+            min,
+            max,
+            avg,
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct DeviceAggregate {
+    device_id: String,
+    window_from: u64,
+    window_to: u64,
+    count: usize,
+    temperature: Option<MetricRollup>,
+    response_time: Option<MetricRollup>,
+    alert_counts: HashMap<String, u32>,
+}
+
+// 디바이스의 키 범위를 스캔해 주어진 시간 윈도우에 대한 집계를 계산한다
+fn aggregate_device(
+    store: &Store,
+    device_id: &str,
+    from: u64,
+    to: u64,
+) -> anyhow::Result<DeviceAggregate> {
+    let prefix = log_key_prefix(device_id);
+    let mut temperatures = Vec::new();
+    let mut response_times = Vec::new();
+    let mut alert_counts: HashMap<String, u32> = HashMap::new();
+    let mut count = 0usize;
+
+    for key in store.get_keys()? {
+        if !key.starts_with(&prefix) {
+            continue;
+        }
+        let Some(bytes) = store.get(&key)? else {
+            continue;
+        };
+        let Ok(entry) = serde_json::from_slice::<StoredLogEntry>(&bytes) else {
+            continue;
+        };
+        if entry.timestamp < from || entry.timestamp > to {
+            continue;
+        }
+        count += 1;
+        if let Some(t) = entry.temperature {
+            temperatures.push(t);
+        }
+        if let Some(rt) = entry.response_time {
+            response_times.push(rt as f32);
+        }
+        for kind in &entry.alert_kinds {
+            *alert_counts.entry(kind.clone()).or_insert(0) += 1;
+        }
+    }
+
+    Ok(DeviceAggregate {
+        device_id: device_id.to_string(),
+        window_from: from,
+        window_to: to,
+        count,
+        temperature: MetricRollup::from_values(&temperatures),
+        response_time: MetricRollup::from_values(&response_times),
+        alert_counts,
+    })
+}
+
+// 디바이스별로 오버라이드 가능한 임계값. 키-값 저장소에 없으면 전역 상수로 폴백한다
+#[derive(Serialize, Deserialize, Clone)]
+struct Thresholds {
+    temperature: f32,
+    response_time: u32,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Thresholds {
+            temperature: TEMPERATURE_THRESHOLD,
+            response_time: RESPONSE_TIME_THRESHOLD,
+        }
+    }
+}
+
+fn thresholds_key(device_id: &str) -> String {
+    format!("devices/{}/thresholds", device_id)
+}
+
+fn load_thresholds(store: &Store, device_id: &str) -> Thresholds {
+    store
+        .get(&thresholds_key(device_id))
+        .ok()
+        .flatten()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+fn save_thresholds(store: &Store, device_id: &str, thresholds: &Thresholds) -> anyhow::Result<()> {
+    store.set(&thresholds_key(device_id), &serde_json::to_vec(thresholds)?)?;
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct ThresholdsUpdate {
+    temperature: Option<f32>,
+    response_time: Option<u32>,
+}
+
+fn now_unix() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// Spin 애플리케이션 변수 `auth_tokens`의 포맷: `<token>:<device_id_prefix>`를 쉼표로 나열.
+// prefix를 생략하거나 `*`로 두면 해당 토큰은 모든 디바이스에 대해 동작하는 관리자급 토큰이 된다.
+// 예: "fleetA-token:sensor-a,fleetB-token:sensor-b,admin-token:*"
+// 이렇게 하면 "다른 디바이스 함대는 서로 다른 자격 증명을 쓸 수 있다"는 목표대로,
+// 한 함대의 토큰으로 다른 함대의 device_id를 조작할 수 없다.
+fn parse_auth_tokens(configured: &str) -> Vec<(String, String)> {
+    configured
+        .split(',')
+        .filter_map(|entry| {
+            let entry = entry.trim();
+            if entry.is_empty() {
+                return None;
+            }
+            let mut parts = entry.splitn(2, ':');
+            let token = parts.next()?.trim();
+            if token.is_empty() {
+                return None;
+            }
+            let scope = parts.next().unwrap_or("*").trim();
+            let scope = if scope.is_empty() { "*" } else { scope };
+            Some((token.to_string(), scope.to_string()))
+        })
+        .collect()
+}
+
+fn extract_bearer(authorization_header: Option<&str>) -> Option<&str> {
+    authorization_header?.strip_prefix("Bearer ")
+}
+
+// `device_id`가 `None`이면 (디바이스 범위가 없는 엔드포인트) 유효한 토큰이기만 하면 통과시키고,
+// `Some`이면 그 토큰의 scope가 `*`이거나 device_id의 접두어와 일치해야 통과시킨다.
+fn authorized(tokens: &[(String, String)], token: Option<&str>, device_id: Option<&str>) -> bool {
+    let Some(token) = token else {
+        return false;
+    };
+    tokens.iter().any(|(t, scope)| {
+        t == token
+            && match device_id {
+                None => true,
+                Some(id) => scope == "*" || id.starts_with(scope.as_str()),
+            }
+    })
+}
+
+fn configured_tokens() -> Vec<(String, String)> {
+    let configured = variables::get("auth_tokens").unwrap_or_default();
+    parse_auth_tokens(&configured)
+}
+
+// 디바이스 범위가 없는 엔드포인트(예: 전체 디바이스 목록)용 인증: 설정된 토큰 중 하나면 통과.
+// 주의: 목록 조회는 scope와 무관하게 모든 디바이스의 식별자를 반환하는 알려진 한계가 있다.
+fn authenticate(req: &Request) -> anyhow::Result<bool> {
+    let header = req.header("authorization").and_then(|v| v.as_str());
+    Ok(authorized(&configured_tokens(), extract_bearer(header), None))
+}
+
+// 특정 device_id에 대한 접근인지까지 검증하는 인증: 토큰의 scope가 이 device_id를 포함해야 통과.
+fn authenticate_for_device(req: &Request, device_id: &str) -> anyhow::Result<bool> {
+    let header = req.header("authorization").and_then(|v| v.as_str());
+    Ok(authorized(&configured_tokens(), extract_bearer(header), Some(device_id)))
+}
+
+fn unauthorized_response() -> anyhow::Result<Response> {
+    Ok(Response::builder()
+        .status(401)
+        .header("content-type", "application/json")
+        .body(serde_json::to_vec(&serde_json::json!({"error": "Unauthorized"}))?)
+        .build())
+}
+
+// 읽기 전용 헬스 체크만 인증 없이 공개한다. 디바이스 텔레메트리를 반환하는 조회는 모두 인증을 거친다
+fn handle_health() -> anyhow::Result<Response> {
+    Ok(Response::builder()
+        .status(200)
+        .header("content-type", "application/json")
+        .body(serde_json::to_vec(&serde_json::json!({"status": "ok"}))?)
+        .build())
 }
 
 #[http_component]
 fn handle_app_rust(req: Request) -> anyhow::Result<impl IntoResponse> {
-    // POST 요청만 처리
-    if *req.method() != Method::Post {
-        return Ok(Response::builder()
+    let store = Store::open(DEVICE_STATE_STORE)?;
+    let path = req.path().to_string();
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+    match (req.method(), segments.as_slice()) {
+        (Method::Get, ["health"]) => handle_health(),
+        (Method::Post, ["api", "v1", "logs"]) => {
+            if !authenticate(&req)? {
+                return unauthorized_response();
+            }
+            handle_ingest(&req, &store)
+        }
+        (Method::Post, ["api", "v1", "logs", "batch"]) => {
+            if !authenticate(&req)? {
+                return unauthorized_response();
+            }
+            handle_batch_ingest(&req, &store)
+        }
+        (Method::Get, ["api", "v1", "devices"]) => {
+            if !authenticate(&req)? {
+                return unauthorized_response();
+            }
+            handle_list_devices(&store)
+        }
+        (Method::Get, ["api", "v1", "devices", device_id]) => {
+            if !authenticate_for_device(&req, device_id)? {
+                return unauthorized_response();
+            }
+            handle_get_device(&store, device_id)
+        }
+        (Method::Get, ["api", "v1", "devices", device_id, "aggregate"]) => {
+            if !authenticate_for_device(&req, device_id)? {
+                return unauthorized_response();
+            }
+            handle_aggregate_query(&req, &store, device_id)
+        }
+        (Method::Put, ["api", "v1", "devices", device_id, "thresholds"]) => {
+            if !authenticate_for_device(&req, device_id)? {
+                return unauthorized_response();
+            }
+            handle_update_thresholds(&req, &store, device_id)
+        }
+        _ => Ok(Response::builder()
             .status(405)
             .header("content-type", "application/json")
             .body(serde_json::to_vec(&serde_json::json!({"error": "Method not allowed"}))?)
-            .build());
+            .build()),
+    }
+}
+
+// POST /api/v1/logs — Content-Type에 따라 JSON(단일/배열) 또는 text/plain 라인 포맷을 받는다
+fn handle_ingest(req: &Request, store: &Store) -> anyhow::Result<Response> {
+    let content_type = req
+        .header("content-type")
+        .and_then(|v| v.as_str())
+        .unwrap_or("application/json");
+    // 미디어 타입은 대소문자를 구분하지 않는다 (RFC 7231) — "Application/JSON"도 허용해야 한다
+    let base_type = content_type.split(';').next().unwrap_or("").trim().to_lowercase();
+
+    match base_type.as_str() {
+        "application/json" | "" => handle_ingest_json(req, store),
+        "text/plain" => handle_ingest_line(req, store),
+        other => Ok(Response::builder()
+            .status(415)
+            .header("content-type", "application/json")
+            .body(serde_json::to_vec(&serde_json::json!({"error": format!("Unsupported content type: {}", other)}))?)
+            .build()),
     }
+}
 
-    // JSON 파싱
+fn handle_ingest_json(req: &Request, store: &Store) -> anyhow::Result<Response> {
     let body = req.body();
+    let is_batch = body
+        .iter()
+        .find(|b| !b.is_ascii_whitespace())
+        .map(|b| *b == b'[')
+        .unwrap_or(false);
+
+    if is_batch {
+        return handle_batch_ingest(req, store);
+    }
+
     let log: Log = match serde_json::from_slice(body) {
         Ok(l) => l,
         Err(e) => {
@@ -51,8 +515,103 @@ fn handle_app_rust(req: Request) -> anyhow::Result<impl IntoResponse> {
         }
     };
 
-    // 로그 분석
-    let (mut result, _is_alert) = analyze_log(&log);
+    // 토큰이 유효하더라도, 이 device_id에 대한 scope가 아니면 거부한다 (다른 함대의 상태를 오염시키지 못하게)
+    if !authenticate_for_device(req, &log.device_id)? {
+        return unauthorized_response();
+    }
+
+    let result = ingest_log(store, &log)?;
+
+    Ok(Response::builder()
+        .status(200)
+        .header("content-type", "application/json")
+        .body(serde_json::to_vec(&result)?)
+        .build())
+}
+
+// text/plain: "temp=81.2;rt=2300;lvl=ERROR;msg=overheat" 같은 경량 라인 포맷을 디코딩한다
+fn handle_ingest_line(req: &Request, store: &Store) -> anyhow::Result<Response> {
+    let text = match std::str::from_utf8(req.body()) {
+        Ok(t) => t,
+        Err(e) => {
+            return Ok(Response::builder()
+                .status(400)
+                .header("content-type", "application/json")
+                .body(serde_json::to_vec(&serde_json::json!({"error": format!("Invalid UTF-8 body: {}", e)}))?)
+                .build());
+        }
+    };
+
+    let log = match parse_line_log(text) {
+        Ok(l) => l,
+        Err(token) => {
+            return Ok(Response::builder()
+                .status(400)
+                .header("content-type", "application/json")
+                .body(serde_json::to_vec(&serde_json::json!({"error": format!("Invalid token: {}", token)}))?)
+                .build());
+        }
+    };
+
+    // 토큰이 유효하더라도, 이 device_id에 대한 scope가 아니면 거부한다 (다른 함대의 상태를 오염시키지 못하게)
+    if !authenticate_for_device(req, &log.device_id)? {
+        return unauthorized_response();
+    }
+
+    let result = ingest_log(store, &log)?;
+
+    Ok(Response::builder()
+        .status(200)
+        .header("content-type", "application/json")
+        .body(serde_json::to_vec(&result)?)
+        .build())
+}
+
+// 알려진 키(temp, rt, lvl, msg, device_id)를 Log 필드로 매핑한다. 잘못된 토큰은 그대로 에러로 돌려준다
+fn parse_line_log(text: &str) -> Result<Log, String> {
+    let mut device_id = None;
+    let mut level = None;
+    let mut response_time = None;
+    let mut temperature = None;
+    let mut message = None;
+
+    for token in text.trim().split(';') {
+        let token = token.trim();
+        if token.is_empty() {
+            continue;
+        }
+
+        let mut parts = token.splitn(2, '=');
+        let key = parts.next().unwrap_or("");
+        let Some(value) = parts.next() else {
+            return Err(token.to_string());
+        };
+
+        match key {
+            "temp" => temperature = Some(value.parse::<f32>().map_err(|_| token.to_string())?),
+            "rt" => response_time = Some(value.parse::<u32>().map_err(|_| token.to_string())?),
+            "lvl" => level = Some(value.to_string()),
+            "msg" => message = Some(value.to_string()),
+            "device_id" => device_id = Some(value.to_string()),
+            _ => {}
+        }
+    }
+
+    Ok(Log {
+        device_id: device_id.unwrap_or_else(default_device_id),
+        level,
+        response_time,
+        temperature,
+        message,
+    })
+}
+
+// 단일 Log를 분석하고 시계열/레지스트리에 반영한다
+fn ingest_log(store: &Store, log: &Log) -> anyhow::Result<AnalysisResult> {
+    let thresholds = load_thresholds(store, &log.device_id);
+
+    // 로그 분석 (키-값 저장소의 디바이스 상태를 갱신)
+    let (result, _is_alert, alert_kinds) = analyze_log(log, store, &thresholds)?;
 
     // 알림 출력 (Spin 로그로 기록 - stdout)
     if !result.alerts.is_empty() {
@@ -61,15 +620,296 @@ fn handle_app_rust(req: Request) -> anyhow::Result<impl IntoResponse> {
         }
     }
 
+    // 시계열 저장 및 디바이스 레지스트리 갱신
+    store_log_entry(store, &log.device_id, now_unix(), log, &alert_kinds)?;
+
+    Ok(result)
+}
+
+#[derive(Serialize)]
+struct BatchItemResult {
+    index: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<AnalysisResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct BatchSummary {
+    total: usize,
+    alerting: usize,
+    ok: usize,
+}
+
+// POST /api/v1/logs/batch — Log 배열을 받아 개별적으로 처리하고, 하나가 망가져도 나머지는 계속 처리한다
+fn handle_batch_ingest(req: &Request, store: &Store) -> anyhow::Result<Response> {
+    let items: Vec<serde_json::Value> = match serde_json::from_slice(req.body()) {
+        Ok(v) => v,
+        Err(e) => {
+            return Ok(Response::builder()
+                .status(400)
+                .header("content-type", "application/json")
+                .body(serde_json::to_vec(&serde_json::json!({"error": format!("Invalid JSON array: {}", e)}))?)
+                .build());
+        }
+    };
+
+    let mut results = Vec::with_capacity(items.len());
+    let mut alerting = 0usize;
+    let mut ok = 0usize;
+
+    for (index, item) in items.into_iter().enumerate() {
+        let log = match serde_json::from_value::<Log>(item) {
+            Ok(log) => log,
+            Err(e) => {
+                results.push(BatchItemResult {
+                    index,
+                    result: None,
+                    error: Some(format!("Invalid log entry: {}", e)),
+                });
+                continue;
+            }
+        };
+
+        // 토큰은 유효하지만 이 device_id의 scope 밖: 배치 전체가 아니라 이 항목만 실패 처리
+        if !authenticate_for_device(req, &log.device_id)? {
+            results.push(BatchItemResult {
+                index,
+                result: None,
+                error: Some(format!("Unauthorized for device_id '{}'", log.device_id)),
+            });
+            continue;
+        }
+
+        match ingest_log(store, &log) {
+            Ok(result) => {
+                if result.status == "ALERT" {
+                    alerting += 1;
+                } else {
+                    ok += 1;
+                }
+                results.push(BatchItemResult {
+                    index,
+                    result: Some(result),
+                    error: None,
+                });
+            }
+            Err(e) => results.push(BatchItemResult {
+                index,
+                result: None,
+                error: Some(e.to_string()),
+            }),
+        }
+    }
+
+    let summary = BatchSummary {
+        total: results.len(),
+        alerting,
+        ok,
+    };
+
     Ok(Response::builder()
         .status(200)
         .header("content-type", "application/json")
-        .body(serde_json::to_vec(&result)?)
+        .body(serde_json::to_vec(&serde_json::json!({"results": results, "summary": summary}))?)
+        .build())
+}
+
+#[derive(Serialize)]
+struct DeviceSummary {
+    device_id: String,
+    first_seen: u64,
+    last_seen: u64,
+    log_count: u64,
+}
+
+// GET /api/v1/devices
+fn handle_list_devices(store: &Store) -> anyhow::Result<Response> {
+    let mut devices = Vec::new();
+    for key in store.get_keys()? {
+        let Some(device_id) = key.strip_prefix("devices/").and_then(|s| s.strip_suffix("/meta")) else {
+            continue;
+        };
+        if let Some(bytes) = store.get(&key)? {
+            if let Ok(entry) = serde_json::from_slice::<DeviceRegistryEntry>(&bytes) {
+                devices.push(DeviceSummary {
+                    device_id: device_id.to_string(),
+                    first_seen: entry.first_seen,
+                    last_seen: entry.last_seen,
+                    log_count: entry.log_count,
+                });
+            }
+        }
+    }
+
+    Ok(Response::builder()
+        .status(200)
+        .header("content-type", "application/json")
+        .body(serde_json::to_vec(&serde_json::json!({"devices": devices}))?)
+        .build())
+}
+
+#[derive(Serialize)]
+struct DeviceDetail {
+    device_id: String,
+    first_seen: u64,
+    last_seen: u64,
+    log_count: u64,
+    state: String,
+    thresholds: Thresholds,
+}
+
+// GET /api/v1/devices/{device_id}
+fn handle_get_device(store: &Store, device_id: &str) -> anyhow::Result<Response> {
+    let registry = store
+        .get(&registry_key(device_id))?
+        .and_then(|bytes| serde_json::from_slice::<DeviceRegistryEntry>(&bytes).ok());
+
+    let Some(registry) = registry else {
+        return Ok(Response::builder()
+            .status(404)
+            .header("content-type", "application/json")
+            .body(serde_json::to_vec(&serde_json::json!({"error": "Unknown device"}))?)
+            .build());
+    };
+
+    let record = DeviceRecord::load(store, device_id);
+    let thresholds = load_thresholds(store, device_id);
+
+    let detail = DeviceDetail {
+        device_id: device_id.to_string(),
+        first_seen: registry.first_seen,
+        last_seen: registry.last_seen,
+        log_count: registry.log_count,
+        state: record.state.as_str().to_string(),
+        thresholds,
+    };
+
+    Ok(Response::builder()
+        .status(200)
+        .header("content-type", "application/json")
+        .body(serde_json::to_vec(&detail)?)
+        .build())
+}
+
+// PUT /api/v1/devices/{device_id}/thresholds
+fn handle_update_thresholds(req: &Request, store: &Store, device_id: &str) -> anyhow::Result<Response> {
+    let update: ThresholdsUpdate = match serde_json::from_slice(req.body()) {
+        Ok(u) => u,
+        Err(e) => {
+            return Ok(Response::builder()
+                .status(400)
+                .header("content-type", "application/json")
+                .body(serde_json::to_vec(&serde_json::json!({"error": format!("Invalid JSON: {}", e)}))?)
+                .build());
+        }
+    };
+
+    let mut thresholds = load_thresholds(store, device_id);
+    if let Some(temperature) = update.temperature {
+        thresholds.temperature = temperature;
+    }
+    if let Some(response_time) = update.response_time {
+        thresholds.response_time = response_time;
+    }
+    save_thresholds(store, device_id, &thresholds)?;
+    // 로그를 한 번도 보내지 않은 디바이스도 레지스트리에 등록해, 방금 설정한 임계값을 GET으로 조회할 수 있게 한다
+    ensure_registered(store, device_id, now_unix())?;
+
+    Ok(Response::builder()
+        .status(200)
+        .header("content-type", "application/json")
+        .body(serde_json::to_vec(&thresholds)?)
+        .build())
+}
+
+// GET /api/v1/devices/{device_id}/aggregate?from=<unix_secs>&to=<unix_secs>
+fn handle_aggregate_query(req: &Request, store: &Store, device_id: &str) -> anyhow::Result<Response> {
+    let params = parse_query(req.query());
+    let now = now_unix();
+    let from = params
+        .get("from")
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(0);
+    let to = params
+        .get("to")
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(now);
+
+    let aggregate = aggregate_device(store, device_id, from, to)?;
+
+    Ok(Response::builder()
+        .status(200)
+        .header("content-type", "application/json")
+        .body(serde_json::to_vec(&aggregate)?)
         .build())
 }
 
-fn analyze_log(log: &Log) -> (AnalysisResult, bool) {
+fn parse_query(query: &str) -> HashMap<String, String> {
+    query
+        .split('&')
+        .filter_map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            let key = parts.next()?;
+            let value = parts.next().unwrap_or("");
+            if key.is_empty() {
+                None
+            } else {
+                Some((key.to_string(), value.to_string()))
+            }
+        })
+        .collect()
+}
+
+// 응답 시간이 회복됐는지 판단한다: 이번 요청에 값이 없으면 마지막으로 실제 관측된 값을 사용한다.
+// 둘 다 없으면(한 번도 측정된 적 없음) 이 지표는 회복을 막을 근거가 없으므로 회복으로 간주한다.
+fn response_time_recovered(current: Option<u32>, last: Option<u32>, threshold: u32) -> bool {
+    match current.or(last) {
+        Some(rt) => rt <= threshold.saturating_sub(RESPONSE_TIME_HYSTERESIS),
+        None => true,
+    }
+}
+
+fn temperature_recovered(current: Option<f32>, last: Option<f32>, threshold: f32) -> bool {
+    match current.or(last) {
+        Some(temp) => temp <= threshold - TEMPERATURE_HYSTERESIS,
+        None => true,
+    }
+}
+
+// 디바운스/히스테리시스 전이 규칙: breach면 연속 위반 횟수를 늘리고 N회째에 Alarm으로,
+// 그렇지 않고 recovered면 즉시 Normal로 리셋, 그 외(여유분 안에 머무는 경우)는 상태를 유지하되
+// Alarm이 아니면 연속 위반 횟수를 0으로 되돌린다.
+fn next_device_state(record: &DeviceRecord, breach: bool, recovered: bool) -> (DeviceState, u32) {
+    if breach {
+        let consecutive_breaches = record.consecutive_breaches.saturating_add(1);
+        let state = if consecutive_breaches >= DEBOUNCE_COUNT {
+            DeviceState::Alarm
+        } else {
+            DeviceState::Warning
+        };
+        (state, consecutive_breaches)
+    } else if recovered {
+        (DeviceState::Normal, 0)
+    } else {
+        // 임계값 아래 값은 연속 위반을 끊는다. Alarm은 다음 위반에서 Warning으로 떨어지지 않도록 카운터를 유지한다
+        let consecutive_breaches = if record.state == DeviceState::Alarm {
+            record.consecutive_breaches
+        } else {
+            0
+        };
+        (record.state, consecutive_breaches)
+    }
+}
+
+fn analyze_log(
+    log: &Log,
+    store: &Store,
+    thresholds: &Thresholds,
+) -> anyhow::Result<(AnalysisResult, bool, Vec<String>)> {
     let mut alerts = Vec::new();
+    let mut alert_kinds = Vec::new();
     let mut is_alert = false;
 
     // ERROR 레벨 감지
@@ -77,40 +917,287 @@ fn analyze_log(log: &Log) -> (AnalysisResult, bool) {
         if level == "ERROR" {
             let msg = log.message.as_deref().unwrap_or("");
             alerts.push(format!("Error detected: {}", msg));
+            alert_kinds.push("error".to_string());
             is_alert = true;
         }
     }
 
     // 응답 시간 임계값 초과
+    let rt_breach = log.response_time.map(|rt| rt > thresholds.response_time).unwrap_or(false);
     if let Some(rt) = log.response_time {
-        if rt > RESPONSE_TIME_THRESHOLD {
+        if rt_breach {
             alerts.push(format!(
                 "High response time: {}ms (threshold: {}ms)",
-                rt, RESPONSE_TIME_THRESHOLD
+                rt, thresholds.response_time
             ));
+            alert_kinds.push("response_time".to_string());
             is_alert = true;
         }
     }
 
     // 온도 임계값 초과
+    let temp_breach = log.temperature.map(|t| t > thresholds.temperature).unwrap_or(false);
     if let Some(temp) = log.temperature {
-        if temp > TEMPERATURE_THRESHOLD {
+        if temp_breach {
             alerts.push(format!(
                 "High temperature: {}C (threshold: {}C)",
-                temp, TEMPERATURE_THRESHOLD
+                temp, thresholds.temperature
             ));
+            alert_kinds.push("temperature".to_string());
             is_alert = true;
         }
     }
 
+    let mut record = DeviceRecord::load(store, &log.device_id);
+    let previous_state = record.state;
+
+    // 히스테리시스 기준 회복 여부: 이번 요청에 값이 없으면 마지막으로 실제 관측된 값을 대신 사용한다.
+    // (단순히 "필드가 없으니 회복된 것으로 본다"고 가정하면, 일부 필드를 생략한 요청 하나만으로
+    // 진짜 측정 없이 Alarm이 풀려버리는 문제가 생긴다)
+    let rt_recovered = response_time_recovered(log.response_time, record.last_response_time, thresholds.response_time);
+    let temp_recovered = temperature_recovered(log.temperature, record.last_temperature, thresholds.temperature);
+    let recovered = rt_recovered && temp_recovered;
+
+    let breach = rt_breach || temp_breach;
+
+    let (state, consecutive_breaches) = next_device_state(&record, breach, recovered);
+    record.state = state;
+    record.consecutive_breaches = consecutive_breaches;
+    // 임계값 아래지만 히스테리시스 여유분 안에 머무는 경우: 상태 유지 (플래핑 방지)
+
+    if let Some(temp) = log.temperature {
+        record.last_temperature = Some(temp);
+    }
+    if let Some(rt) = log.response_time {
+        record.last_response_time = Some(rt);
+    }
+    record.last_ts = now_unix();
+    record.save(store, &log.device_id)?;
+
+    let state_changed = record.state != previous_state;
     let status = if is_alert { "ALERT" } else { "OK" };
 
-    (
+    Ok((
         AnalysisResult {
             status: status.to_string(),
             alerts,
             device_id: log.device_id.clone(),
+            state: record.state.as_str().to_string(),
+            state_changed,
         },
         is_alert,
-    )
+        alert_kinds,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record_in(state: DeviceState, consecutive_breaches: u32) -> DeviceRecord {
+        DeviceRecord {
+            state,
+            consecutive_breaches,
+            last_temperature: None,
+            last_response_time: None,
+            last_ts: 0,
+        }
+    }
+
+    #[test]
+    fn breach_progresses_through_warning_to_alarm_after_debounce_count() {
+        let mut record = DeviceRecord::initial();
+
+        let (state, count) = next_device_state(&record, true, false);
+        assert_eq!(state, DeviceState::Warning);
+        assert_eq!(count, 1);
+        record.state = state;
+        record.consecutive_breaches = count;
+
+        let (state, count) = next_device_state(&record, true, false);
+        assert_eq!(state, DeviceState::Warning);
+        assert_eq!(count, 2);
+        record.state = state;
+        record.consecutive_breaches = count;
+
+        // DEBOUNCE_COUNT = 3: the third consecutive breach trips Alarm
+        let (state, count) = next_device_state(&record, true, false);
+        assert_eq!(state, DeviceState::Alarm);
+        assert_eq!(count, 3);
+    }
+
+    #[test]
+    fn alarm_clears_only_once_recovered() {
+        let record = record_in(DeviceState::Alarm, 5);
+
+        // Not breaching but not yet recovered (inside the hysteresis band): stays in Alarm.
+        let (state, count) = next_device_state(&record, false, false);
+        assert_eq!(state, DeviceState::Alarm);
+        assert_eq!(count, 5);
+
+        // Recovered: resets to Normal and clears the debounce counter.
+        let (state, count) = next_device_state(&record, false, true);
+        assert_eq!(state, DeviceState::Normal);
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn hysteresis_band_holds_state_but_breaks_breach_streak() {
+        // Value dropped below the raw threshold but not below threshold - hysteresis:
+        // this is neither a fresh breach nor a recovery, so the state must not flap,
+        // but the run of consecutive breaches is broken.
+        let record = record_in(DeviceState::Warning, 2);
+        let (state, count) = next_device_state(&record, false, false);
+        assert_eq!(state, DeviceState::Warning);
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn omitted_metric_falls_back_to_last_observed_value_for_recovery() {
+        // Temperature was last seen well above the alarm threshold; a log that omits
+        // temperature entirely must not be treated as "recovered" for that metric.
+        let recovered = temperature_recovered(None, Some(95.0), TEMPERATURE_THRESHOLD);
+        assert!(!recovered);
+
+        let recovered = response_time_recovered(None, Some(3000), RESPONSE_TIME_THRESHOLD);
+        assert!(!recovered);
+    }
+
+    #[test]
+    fn metric_with_no_history_is_vacuously_recovered() {
+        // A metric that has never been reported has no evidence of a breach, so it
+        // must not block recovery on its own.
+        assert!(temperature_recovered(None, None, TEMPERATURE_THRESHOLD));
+        assert!(response_time_recovered(None, None, RESPONSE_TIME_THRESHOLD));
+    }
+
+    #[test]
+    fn present_metric_below_hysteresis_band_recovers() {
+        assert!(temperature_recovered(Some(70.0), Some(95.0), TEMPERATURE_THRESHOLD));
+        assert!(response_time_recovered(Some(1000), Some(3000), RESPONSE_TIME_THRESHOLD));
+    }
+
+    #[test]
+    fn present_metric_inside_hysteresis_band_does_not_recover() {
+        // Just under the raw threshold but still above threshold - hysteresis.
+        assert!(!temperature_recovered(Some(78.0), None, TEMPERATURE_THRESHOLD));
+        assert!(!response_time_recovered(Some(1900), None, RESPONSE_TIME_THRESHOLD));
+    }
+
+    #[test]
+    fn parse_line_log_maps_known_keys() {
+        let log = parse_line_log("temp=81.2;rt=2300;lvl=ERROR;msg=overheat;device_id=sensor-1").unwrap();
+        assert_eq!(log.device_id, "sensor-1");
+        assert_eq!(log.temperature, Some(81.2));
+        assert_eq!(log.response_time, Some(2300));
+        assert_eq!(log.level.as_deref(), Some("ERROR"));
+        assert_eq!(log.message.as_deref(), Some("overheat"));
+    }
+
+    #[test]
+    fn parse_line_log_defaults_device_id_when_absent() {
+        let log = parse_line_log("temp=50.0").unwrap();
+        assert_eq!(log.device_id, "unknown");
+    }
+
+    #[test]
+    fn parse_line_log_ignores_unknown_keys() {
+        let log = parse_line_log("temp=50.0;battery=98").unwrap();
+        assert_eq!(log.temperature, Some(50.0));
+    }
+
+    #[test]
+    fn parse_line_log_rejects_token_without_equals() {
+        let err = parse_line_log("temp=50.0;garbled").unwrap_err();
+        assert_eq!(err, "garbled");
+    }
+
+    #[test]
+    fn parse_line_log_rejects_non_numeric_temperature() {
+        let err = parse_line_log("temp=hot").unwrap_err();
+        assert_eq!(err, "temp=hot");
+    }
+
+    #[test]
+    fn parse_line_log_rejects_non_numeric_response_time() {
+        let err = parse_line_log("rt=slow").unwrap_err();
+        assert_eq!(err, "rt=slow");
+    }
+
+    #[test]
+    fn parse_line_log_tolerates_blank_and_whitespace_tokens() {
+        let log = parse_line_log(" temp=60.0 ; ; lvl=INFO ").unwrap();
+        assert_eq!(log.temperature, Some(60.0));
+        assert_eq!(log.level.as_deref(), Some("INFO"));
+    }
+
+    #[test]
+    fn extract_bearer_requires_bearer_scheme() {
+        assert_eq!(extract_bearer(Some("Bearer abc123")), Some("abc123"));
+        assert_eq!(extract_bearer(Some("Basic abc123")), None);
+        assert_eq!(extract_bearer(None), None);
+    }
+
+    #[test]
+    fn parse_auth_tokens_supports_scoped_and_wildcard_entries() {
+        let tokens = parse_auth_tokens("fleetA-token:sensor-a,fleetB-token:sensor-b,admin-token:*");
+        assert_eq!(
+            tokens,
+            vec![
+                ("fleetA-token".to_string(), "sensor-a".to_string()),
+                ("fleetB-token".to_string(), "sensor-b".to_string()),
+                ("admin-token".to_string(), "*".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_auth_tokens_defaults_missing_scope_to_wildcard() {
+        let tokens = parse_auth_tokens("legacy-token");
+        assert_eq!(tokens, vec![("legacy-token".to_string(), "*".to_string())]);
+    }
+
+    #[test]
+    fn parse_auth_tokens_ignores_blank_entries() {
+        let tokens = parse_auth_tokens(" , fleetA-token:sensor-a , , ");
+        assert_eq!(tokens, vec![("fleetA-token".to_string(), "sensor-a".to_string())]);
+    }
+
+    #[test]
+    fn parse_auth_tokens_empty_config_yields_no_tokens() {
+        assert!(parse_auth_tokens("").is_empty());
+    }
+
+    #[test]
+    fn authorized_rejects_missing_token() {
+        let tokens = parse_auth_tokens("fleetA-token:sensor-a");
+        assert!(!authorized(&tokens, None, Some("sensor-a-1")));
+    }
+
+    #[test]
+    fn authorized_rejects_empty_configuration() {
+        let tokens = parse_auth_tokens("");
+        assert!(!authorized(&tokens, Some("any-token"), None));
+    }
+
+    #[test]
+    fn authorized_scoped_token_only_matches_its_device_prefix() {
+        let tokens = parse_auth_tokens("fleetA-token:sensor-a,fleetB-token:sensor-b");
+        assert!(authorized(&tokens, Some("fleetA-token"), Some("sensor-a-42")));
+        assert!(!authorized(&tokens, Some("fleetA-token"), Some("sensor-b-1")));
+    }
+
+    #[test]
+    fn authorized_wildcard_token_matches_any_device() {
+        let tokens = parse_auth_tokens("admin-token:*");
+        assert!(authorized(&tokens, Some("admin-token"), Some("sensor-a-1")));
+        assert!(authorized(&tokens, Some("admin-token"), Some("sensor-z-9")));
+    }
+
+    #[test]
+    fn authorized_with_no_device_id_accepts_any_valid_token_regardless_of_scope() {
+        // Used for endpoints that aren't scoped to a single device (e.g. the device list).
+        let tokens = parse_auth_tokens("fleetA-token:sensor-a");
+        assert!(authorized(&tokens, Some("fleetA-token"), None));
+    }
 }